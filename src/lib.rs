@@ -1,24 +1,124 @@
 use clap::{arg, command};
 use colored::Colorize;
-use regex::{Captures, Regex, RegexBuilder};
+use ignore::WalkBuilder;
+use pcre2::bytes::RegexBuilder as Pcre2RegexBuilder;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use regex::{Captures, Regex, RegexBuilder, RegexSet};
+use serde::Serialize;
+use std::collections::VecDeque;
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::{fs, io};
-use walkdir::WalkDir;
 
-type MyResult<T> = Result<T, Box<dyn Error>>;
+type MyResult<T> = Result<T, Box<dyn Error + Send + Sync>>;
 
-type FmtStrategy = fn(&Regex, &str) -> String;
+type FmtStrategy = fn(&PatternEngine, &str) -> MyResult<String>;
+
+/// Wraps either the default `regex` backend or, behind `-P/--pcre2`, a PCRE2
+/// backend that supports lookaround and backreferences. The rest of the
+/// pipeline only ever talks to this enum, so it doesn't need to care which
+/// backend compiled the pattern.
+enum PatternEngine {
+    Regex(Regex),
+    Pcre2(pcre2::bytes::Regex),
+}
+
+impl Debug for PatternEngine {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Regex(re) => write!(f, "PatternEngine::Regex({:?})", re),
+            Self::Pcre2(_) => write!(f, "PatternEngine::Pcre2"),
+        }
+    }
+}
+
+impl PatternEngine {
+    fn new(pattern: &str, case_insensitive: bool, pcre2: bool) -> MyResult<Self> {
+        if pcre2 {
+            let re = Pcre2RegexBuilder::new()
+                .caseless(case_insensitive)
+                .build(pattern)
+                .map_err(|_| format!("Invalid pattern \"{}\"", pattern))?;
+
+            Ok(Self::Pcre2(re))
+        } else {
+            let mut regex_builder = RegexBuilder::new(pattern);
+            regex_builder.case_insensitive(case_insensitive);
+            let re = regex_builder
+                .build()
+                .map_err(|_| format!("Invalid pattern \"{}\"", pattern))?;
+
+            Ok(Self::Regex(re))
+        }
+    }
+
+    fn is_match(&self, text: &str) -> MyResult<bool> {
+        match self {
+            Self::Regex(re) => Ok(re.is_match(text)),
+            Self::Pcre2(re) => Ok(re.is_match(text.as_bytes())?),
+        }
+    }
+
+    fn find_submatches(&self, text: &str) -> MyResult<Vec<Submatch>> {
+        match self {
+            Self::Regex(re) => Ok(re.find_iter(text).map(Submatch::from).collect()),
+            Self::Pcre2(re) => re
+                .find_iter(text.as_bytes())
+                .map(|m| {
+                    let m = m?;
+                    Ok(Submatch {
+                        start: m.start(),
+                        end: m.end(),
+                        text: text[m.start()..m.end()].to_string(),
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// The `replace_all`-equivalent shared by both backends: wraps every
+    /// match in the line with red coloring.
+    fn highlight_all_matches_red(&self, line: &str) -> MyResult<String> {
+        match self {
+            Self::Regex(re) => Ok(re
+                .replace_all(line, |caps: &Captures| format!("{}", &caps[0].red()))
+                .to_string()),
+            Self::Pcre2(re) => {
+                let mut result = String::new();
+                let mut last = 0;
+
+                for m in re.find_iter(line.as_bytes()) {
+                    let m = m?;
+                    result.push_str(&line[last..m.start()]);
+                    result.push_str(&format!("{}", &line[m.start()..m.end()].red()));
+                    last = m.end();
+                }
+                result.push_str(&line[last..]);
+
+                Ok(result)
+            }
+        }
+    }
+}
 
 #[derive(Debug)]
 pub struct Config {
-    pattern: Regex,
+    pattern: PatternEngine,
     files: Vec<String>,
     recursive: bool,
     count: bool,
     invert_match: bool,
+    before_context: usize,
+    after_context: usize,
+    globs: Vec<String>,
+    threads: usize,
+    hidden: bool,
+    no_ignore: bool,
+    follow: bool,
+    json: bool,
 }
 
 pub fn get_args() -> MyResult<Config> {
@@ -33,31 +133,154 @@ pub fn get_args() -> MyResult<Config> {
             arg!(count: -c --count "Count occurrences"),
             arg!(invert_match: -v --"invert-match" "Invert match"),
             arg!(insensitive: -i --insensitive "Case-insensitive"),
+            arg!(before_context: -B --"before-context" <NUM> "Print NUM lines of leading context")
+                .required(false),
+            arg!(after_context: -A --"after-context" <NUM> "Print NUM lines of trailing context")
+                .required(false),
+            arg!(context: -C --context <NUM> "Print NUM lines of leading and trailing context")
+                .required(false),
+            arg!(globs: -g --glob <GLOB> "Include (or, prefixed with !, exclude) files matching glob")
+                .required(false)
+                .multiple_occurrences(true),
+            arg!(threads: -j --threads <NUM> "Number of threads to search with")
+                .required(false),
+            arg!(hidden: --hidden "Search hidden files and directories"),
+            arg!(no_ignore: --"no-ignore" "Don't respect .gitignore/.ignore files"),
+            arg!(follow: --follow "Follow symbolic links"),
+            arg!(json: --json "Print matches as JSON Lines"),
+            arg!(pcre2: -P --pcre2 "Use PCRE2 syntax (lookaround, backreferences)"),
         ])
         .get_matches();
 
     let pattern = matches
         .value_of("pattern")
         .map(|pattern| {
-            let mut regex_builder = RegexBuilder::new(pattern);
-            regex_builder.case_insensitive(matches.is_present("insensitive"));
-            regex_builder
-                .build()
-                .map_err(|_| format!("Invalid pattern \"{}\"", pattern))
+            PatternEngine::new(
+                pattern,
+                matches.is_present("insensitive"),
+                matches.is_present("pcre2"),
+            )
         })
         .transpose()?
         .unwrap();
 
+    let context: Option<usize> = matches
+        .value_of("context")
+        .map(|n| {
+            n.parse()
+                .map_err(|_| format!("Invalid context value \"{}\"", n))
+        })
+        .transpose()?;
+
+    let before_arg: Option<usize> = matches
+        .value_of("before_context")
+        .map(|n| {
+            n.parse()
+                .map_err(|_| format!("Invalid before-context value \"{}\"", n))
+        })
+        .transpose()?;
+
+    let after_arg: Option<usize> = matches
+        .value_of("after_context")
+        .map(|n| {
+            n.parse()
+                .map_err(|_| format!("Invalid after-context value \"{}\"", n))
+        })
+        .transpose()?;
+
+    // `-C` combines with `-A`/`-B` rather than overriding them, matching
+    // `grep`: `-A5 -C2` prints 5 lines of trailing context, the max of the two.
+    let before_context = before_arg.unwrap_or(0).max(context.unwrap_or(0));
+    let after_context = after_arg.unwrap_or(0).max(context.unwrap_or(0));
+
+    let globs = matches
+        .values_of("globs")
+        .map(|vs| vs.map(String::from).collect())
+        .unwrap_or_default();
+
+    let threads = matches
+        .value_of("threads")
+        .map(|n| {
+            n.parse()
+                .map_err(|_| format!("Invalid threads value \"{}\"", n))
+        })
+        .transpose()?
+        .unwrap_or_else(num_cpus::get);
+
     Ok(Config {
         pattern,
         files: matches.values_of_t_or_exit("files"),
         recursive: matches.is_present("recursive"),
         count: matches.is_present("count"),
         invert_match: matches.is_present("invert_match"),
+        before_context,
+        after_context,
+        globs,
+        threads,
+        hidden: matches.is_present("hidden"),
+        no_ignore: matches.is_present("no_ignore"),
+        follow: matches.is_present("follow"),
+        json: matches.is_present("json"),
     })
 }
 
-fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
+/// Translates a glob pattern into an anchored regex, per the substitution
+/// order `**/` -> `(?:.*/)?`, `*` -> `[^/]*`, `?` -> `[^/]`.
+fn glob_to_regex(glob: &str) -> String {
+    let escaped = regex::escape(glob);
+    let translated = escaped
+        .replace("\\*\\*/", "(?:.*/)?")
+        .replace("\\*", "[^/]*")
+        .replace("\\?", "[^/]");
+
+    format!("^{}$", translated)
+}
+
+/// Compiles `-g/--glob` patterns into a positive and a negative `RegexSet`,
+/// combining the ripgrep-style "match any positive set member and no
+/// negative member" rule into a single pass over each candidate path.
+struct GlobFilter {
+    include: RegexSet,
+    exclude: RegexSet,
+    has_include: bool,
+}
+
+impl GlobFilter {
+    fn new(patterns: &[String]) -> MyResult<Self> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+
+        for pattern in patterns {
+            match pattern.strip_prefix('!') {
+                Some(negated) => exclude.push(glob_to_regex(negated)),
+                None => include.push(glob_to_regex(pattern)),
+            }
+        }
+
+        let has_include = !include.is_empty();
+
+        Ok(Self {
+            include: RegexSet::new(include)?,
+            exclude: RegexSet::new(exclude)?,
+            has_include,
+        })
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        (!self.has_include || self.include.is_match(path)) && !self.exclude.is_match(path)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn find_files(
+    paths: &[String],
+    recursive: bool,
+    globs: &[String],
+    hidden: bool,
+    no_ignore: bool,
+    follow: bool,
+) -> MyResult<Vec<MyResult<String>>> {
+    let glob_filter = GlobFilter::new(globs)?;
     let mut res = Vec::new();
 
     for path in paths {
@@ -67,12 +290,29 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
                 Ok(metadata) => {
                     if metadata.is_dir() {
                         if recursive {
-                            res.extend(WalkDir::new(path).into_iter().filter_map(|dir_entry| {
-                                match dir_entry {
-                                    Ok(entry) if entry.file_type().is_dir() => None,
-                                    Ok(entry) => Some(Ok(entry.path().display().to_string())),
-                                    Err(err) => Some(Err(err.into())),
+                            let walk = WalkBuilder::new(path)
+                                .hidden(!hidden)
+                                .ignore(!no_ignore)
+                                .git_ignore(!no_ignore)
+                                .git_global(!no_ignore)
+                                .git_exclude(!no_ignore)
+                                .parents(!no_ignore)
+                                .follow_links(follow)
+                                .build();
+
+                            res.extend(walk.filter_map(|dir_entry| match dir_entry {
+                                Ok(entry) if entry.file_type().is_some_and(|ft| ft.is_dir()) => {
+                                    None
+                                }
+                                Ok(entry) => {
+                                    let path = entry.path().display().to_string();
+                                    if glob_filter.is_match(&path) {
+                                        Some(Ok(path))
+                                    } else {
+                                        None
+                                    }
                                 }
+                                Err(err) => Some(Err(err.into())),
                             }))
                         } else {
                             res.push(Err(format!("{} is a directory", path).into()));
@@ -86,7 +326,7 @@ fn find_files(paths: &[String], recursive: bool) -> Vec<MyResult<String>> {
         }
     }
 
-    res
+    Ok(res)
 }
 
 fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
@@ -96,38 +336,114 @@ fn open(filename: &str) -> MyResult<Box<dyn BufRead>> {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+struct Submatch {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+impl From<regex::Match<'_>> for Submatch {
+    fn from(m: regex::Match<'_>) -> Self {
+        Self {
+            start: m.start(),
+            end: m.end(),
+            text: m.as_str().to_string(),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct MatchedLine {
     line: usize,
     content: String,
+    raw: String,
+    is_match: bool,
+    submatches: Vec<Submatch>,
 }
 
 impl MatchedLine {
-    pub fn new(line: usize, content: String) -> Self {
-        Self { line, content }
+    pub fn new(
+        line: usize,
+        content: String,
+        raw: String,
+        is_match: bool,
+        submatches: Vec<Submatch>,
+    ) -> Self {
+        Self {
+            line,
+            content,
+            raw,
+            is_match,
+            submatches,
+        }
     }
 }
 
 impl Display for MatchedLine {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:>6}:{}", self.line.to_string().cyan(), self.content)
+        let sep = if self.is_match { ":" } else { "-" };
+        write!(
+            f,
+            "{:>6}{}{}",
+            self.line.to_string().cyan(),
+            sep,
+            self.content
+        )
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn find_lines_and_fmt_with<T: BufRead>(
     mut file: T,
-    pattern: &Regex,
+    pattern: &PatternEngine,
     invert_match: bool,
+    before: usize,
+    after: usize,
     fmt_strategy: FmtStrategy,
 ) -> MyResult<Vec<MatchedLine>> {
     let mut res = Vec::new();
     let mut line = String::new();
     let mut line_num: usize = 0;
+    let mut before_buf: VecDeque<(usize, String)> = VecDeque::with_capacity(before);
+    let mut after_remaining: usize = 0;
+
     while file.read_line(&mut line)? > 0 {
         line_num += 1;
 
-        if pattern.is_match(&line) ^ invert_match {
-            res.push(MatchedLine::new(line_num, fmt_strategy(pattern, &line)))
+        if pattern.is_match(&line)? ^ invert_match {
+            for (ctx_line, ctx_content) in before_buf.drain(..) {
+                res.push(MatchedLine::new(
+                    ctx_line,
+                    ctx_content.clone(),
+                    ctx_content,
+                    false,
+                    Vec::new(),
+                ));
+            }
+            let submatches = pattern.find_submatches(&line)?;
+            res.push(MatchedLine::new(
+                line_num,
+                fmt_strategy(pattern, &line)?,
+                line.clone(),
+                true,
+                submatches,
+            ));
+            after_remaining = after;
+        } else if after_remaining > 0 {
+            res.push(MatchedLine::new(
+                line_num,
+                line.clone(),
+                line.clone(),
+                false,
+                Vec::new(),
+            ));
+            after_remaining -= 1;
+        } else if before > 0 {
+            if before_buf.len() == before {
+                before_buf.pop_front();
+            }
+            before_buf.push_back((line_num, line.clone()));
         }
 
         line.clear();
@@ -139,72 +455,213 @@ fn find_lines_and_fmt_with<T: BufRead>(
 #[allow(dead_code)]
 fn find_lines_with_default_strategy<T: BufRead>(
     file: T,
-    pattern: &Regex,
+    pattern: &PatternEngine,
     invert_match: bool,
+    before: usize,
+    after: usize,
 ) -> MyResult<Vec<MatchedLine>> {
-    find_lines_and_fmt_with(file, pattern, invert_match, default_fmt_strategy)
+    find_lines_and_fmt_with(file, pattern, invert_match, before, after, default_fmt_strategy)
 }
 
 fn find_lines_with_highlight_all_matches_red<T: BufRead>(
     file: T,
-    pattern: &Regex,
+    pattern: &PatternEngine,
     invert_match: bool,
+    before: usize,
+    after: usize,
 ) -> MyResult<Vec<MatchedLine>> {
-    find_lines_and_fmt_with(file, pattern, invert_match, highlight_all_matches_red)
+    find_lines_and_fmt_with(
+        file,
+        pattern,
+        invert_match,
+        before,
+        after,
+        highlight_all_matches_red,
+    )
 }
 
-fn highlight_all_matches_red(pattern: &Regex, line: &str) -> String {
-    pattern
-        .replace_all(line, |caps: &Captures| format!("{}", &caps[0].red()))
-        .to_string()
+fn highlight_all_matches_red(pattern: &PatternEngine, line: &str) -> MyResult<String> {
+    pattern.highlight_all_matches_red(line)
 }
 
 #[allow(dead_code)]
-fn default_fmt_strategy(_pattern: &Regex, line: &str) -> String {
-    line.to_string()
+fn default_fmt_strategy(_pattern: &PatternEngine, line: &str) -> MyResult<String> {
+    Ok(line.to_string())
 }
 
-pub fn run(config: Config) -> MyResult<()> {
-    let entries = find_files(&config.files, config.recursive);
-
-    for entry in &entries {
-        match entry {
-            Err(e) => eprintln!("{}", e),
-            Ok(filename) => match open(filename) {
-                Err(err) => eprintln!("{}: {}", filename, err),
-                Ok(file) => {
-                    let matches = find_lines_with_highlight_all_matches_red(
-                        file,
-                        &config.pattern,
-                        config.invert_match,
-                    )?;
-
-                    let filename = filename.green();
-                    let matches_num = matches.len();
-
-                    if entries.len() > 1 {
-                        if config.count {
-                            println!("{}:{}", filename, matches_num);
-                        } else {
-                            if matches_num > 0 {
-                                println!("{}", filename);
-                            }
+/// Whether a `--` group separator belongs between `prev_line` and `line`.
+/// `group_context` should be false when no `-A/-B/-C` context was requested,
+/// since `matches` then holds only match lines and a line-number gap carries
+/// no meaning (grep/ripgrep never emit `--` without context).
+fn needs_group_separator(prev_line: Option<usize>, line: usize, group_context: bool) -> bool {
+    group_context && prev_line.is_some_and(|prev| line > prev + 1)
+}
 
-                            for line in matches {
-                                print!("{}", line);
-                            }
-                        }
-                    } else {
-                        if config.count {
-                            println!("{}", matches_num);
-                        } else {
-                            for line in matches {
-                                print!("{}", line);
-                            }
-                        }
-                    }
+/// Prints matched lines, grouping disjoint context windows with a `--`
+/// separator (see `needs_group_separator`).
+fn print_matches(matches: &[MatchedLine], group_context: bool) {
+    let mut prev_line: Option<usize> = None;
+
+    for line in matches {
+        if needs_group_separator(prev_line, line.line, group_context) {
+            println!("--");
+        }
+
+        print!("{}", line);
+        prev_line = Some(line.line);
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonRecord {
+    path: String,
+    line_number: usize,
+    line: String,
+    submatches: Vec<Submatch>,
+}
+
+/// A pluggable sink for search results, so the text and `--json` output
+/// modes can share the same search core in `run`.
+trait Printer {
+    fn print(&self, filename: &str, matches: &[MatchedLine], multi_file: bool, count: bool);
+}
+
+struct TextPrinter {
+    group_context: bool,
+}
+
+impl Printer for TextPrinter {
+    fn print(&self, filename: &str, matches: &[MatchedLine], multi_file: bool, count: bool) {
+        let filename = filename.green();
+        let matches_num = matches.iter().filter(|m| m.is_match).count();
+
+        if multi_file {
+            if count {
+                println!("{}:{}", filename, matches_num);
+            } else {
+                if matches_num > 0 {
+                    println!("{}", filename);
                 }
-            },
+
+                print_matches(matches, self.group_context);
+            }
+        } else if count {
+            println!("{}", matches_num);
+        } else {
+            print_matches(matches, self.group_context);
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JsonCountRecord {
+    path: String,
+    count: usize,
+}
+
+struct JsonPrinter;
+
+impl Printer for JsonPrinter {
+    fn print(&self, filename: &str, matches: &[MatchedLine], _multi_file: bool, count: bool) {
+        if count {
+            let record = JsonCountRecord {
+                path: filename.to_string(),
+                count: matches.iter().filter(|line| line.is_match).count(),
+            };
+
+            match serde_json::to_string(&record) {
+                Ok(json) => println!("{}", json),
+                Err(err) => eprintln!("{}", err),
+            }
+
+            return;
+        }
+
+        for line in matches.iter().filter(|line| line.is_match) {
+            let record = JsonRecord {
+                path: filename.to_string(),
+                line_number: line.line,
+                line: line.raw.clone(),
+                submatches: line.submatches.clone(),
+            };
+
+            match serde_json::to_string(&record) {
+                Ok(json) => println!("{}", json),
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+    }
+}
+
+enum SearchOutcome {
+    EntryError(Box<dyn Error + Send + Sync>),
+    OpenError {
+        filename: String,
+        err: Box<dyn Error + Send + Sync>,
+    },
+    Found {
+        filename: String,
+        matches: Vec<MatchedLine>,
+    },
+}
+
+fn search_entry(entry: &MyResult<String>, config: &Config) -> MyResult<SearchOutcome> {
+    match entry {
+        Err(e) => Ok(SearchOutcome::EntryError(format!("{}", e).into())),
+        Ok(filename) => match open(filename) {
+            Err(err) => Ok(SearchOutcome::OpenError {
+                filename: filename.clone(),
+                err,
+            }),
+            Ok(file) => {
+                let matches = find_lines_with_highlight_all_matches_red(
+                    file,
+                    &config.pattern,
+                    config.invert_match,
+                    config.before_context,
+                    config.after_context,
+                )?;
+
+                Ok(SearchOutcome::Found {
+                    filename: filename.clone(),
+                    matches,
+                })
+            }
+        },
+    }
+}
+
+pub fn run(config: Config) -> MyResult<()> {
+    let entries = find_files(
+        &config.files,
+        config.recursive,
+        &config.globs,
+        config.hidden,
+        config.no_ignore,
+        config.follow,
+    )?;
+
+    // Search every file on a capped rayon pool; par_iter().collect() keeps
+    // the results in input order regardless of which thread found them.
+    let pool = ThreadPoolBuilder::new().num_threads(config.threads).build()?;
+    let outcomes: Vec<MyResult<SearchOutcome>> =
+        pool.install(|| entries.par_iter().map(|entry| search_entry(entry, &config)).collect());
+
+    let printer: Box<dyn Printer> = if config.json {
+        Box::new(JsonPrinter)
+    } else {
+        Box::new(TextPrinter {
+            group_context: config.before_context > 0 || config.after_context > 0,
+        })
+    };
+
+    for outcome in outcomes {
+        match outcome? {
+            SearchOutcome::EntryError(e) => eprintln!("{}", e),
+            SearchOutcome::OpenError { filename, err } => eprintln!("{}: {}", filename, err),
+            SearchOutcome::Found { filename, matches } => {
+                printer.print(&filename, &matches, entries.len() > 1, config.count);
+            }
         }
     }
     Ok(())
@@ -213,27 +670,37 @@ pub fn run(config: Config) -> MyResult<()> {
 #[cfg(test)]
 mod tests {
     use super::find_files;
+    use super::needs_group_separator;
+    use super::GlobFilter;
+    use super::JsonCountRecord;
+    use super::JsonRecord;
+    use super::MyResult;
+    use super::PatternEngine;
+    use super::SearchOutcome;
+    use super::Submatch;
+    use super::{search_entry, Config};
     use crate::find_lines_with_default_strategy;
     use rand::{distributions::Alphanumeric, Rng};
-    use regex::{Regex, RegexBuilder};
+    use rayon::prelude::*;
+    use rayon::ThreadPoolBuilder;
     use std::io::Cursor;
 
     #[test]
     fn test_find_files() {
         // Verify that the function finds a file known to exist
-        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false);
+        let files = find_files(&["./tests/inputs/fox.txt".to_string()], false, &[], false, false, false).unwrap();
         assert_eq!(files.len(), 1);
         assert_eq!(files[0].as_ref().unwrap(), "./tests/inputs/fox.txt");
 
         // The function should reject a directory without the recursive option
-        let files = find_files(&["./tests/inputs".to_string()], false);
+        let files = find_files(&["./tests/inputs".to_string()], false, &[], false, false, false).unwrap();
         assert_eq!(files.len(), 1);
         if let Err(e) = &files[0] {
             assert_eq!(e.to_string(), "./tests/inputs is a directory");
         }
 
         // Verify the function finds four files in the directory recursively
-        let res = find_files(&["./tests/inputs".to_string()], true);
+        let res = find_files(&["./tests/inputs".to_string()], true, &[], false, false, false).unwrap();
         let mut files: Vec<String> = res
             .iter()
             .map(|r| r.as_ref().unwrap().replace("\\", "/"))
@@ -258,35 +725,290 @@ mod tests {
             .collect();
 
         // Verify that the function returns the bad file as an error
-        let files = find_files(&[bad], false);
+        let files = find_files(&[bad], false, &[], false, false, false).unwrap();
         assert_eq!(files.len(), 1);
         assert!(files[0].is_err());
     }
 
+    fn matched_files(res: &[MyResult<String>]) -> Vec<String> {
+        let mut files: Vec<String> = res
+            .iter()
+            .map(|r| r.as_ref().unwrap().replace("\\", "/"))
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_find_files_respects_gitignore_and_hidden_by_default() {
+        // `tests/ignore_fixture` has a visible file, a file excluded
+        // by its own `.gitignore`, and a dotfile.
+        let dir = "./tests/ignore_fixture".to_string();
+
+        // By default, both the gitignored file and the dotfile are skipped.
+        let res = find_files(std::slice::from_ref(&dir), true, &[], false, false, false).unwrap();
+        assert_eq!(
+            matched_files(&res),
+            vec!["./tests/ignore_fixture/visible.txt"]
+        );
+
+        // `--hidden` brings dotfiles back (including the fixture's own
+        // `.gitignore`), but not the gitignored file.
+        let res = find_files(std::slice::from_ref(&dir), true, &[], true, false, false).unwrap();
+        assert_eq!(
+            matched_files(&res),
+            vec![
+                "./tests/ignore_fixture/.gitignore",
+                "./tests/ignore_fixture/.hidden.txt",
+                "./tests/ignore_fixture/visible.txt",
+            ]
+        );
+
+        // `--no-ignore` brings the gitignored file back, but not the dotfile.
+        let res = find_files(std::slice::from_ref(&dir), true, &[], false, true, false).unwrap();
+        assert_eq!(
+            matched_files(&res),
+            vec![
+                "./tests/ignore_fixture/ignored.txt",
+                "./tests/ignore_fixture/visible.txt",
+            ]
+        );
+
+        // Both flags together surface every file, including `.gitignore`.
+        let res = find_files(std::slice::from_ref(&dir), true, &[], true, true, false).unwrap();
+        assert_eq!(
+            matched_files(&res),
+            vec![
+                "./tests/ignore_fixture/.gitignore",
+                "./tests/ignore_fixture/.hidden.txt",
+                "./tests/ignore_fixture/ignored.txt",
+                "./tests/ignore_fixture/visible.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search_entry_results_stay_in_input_order() {
+        // Search several files across multiple threads, where they need not
+        // finish in submission order, then confirm par_iter()'s collect()
+        // still hands the results back in input order.
+        let files = vec![
+            "./tests/ignore_fixture/ignored.txt".to_string(),
+            "./tests/ignore_fixture/visible.txt".to_string(),
+            "./tests/ignore_fixture/.hidden.txt".to_string(),
+        ];
+        let entries: Vec<MyResult<String>> = files.iter().cloned().map(Ok).collect();
+
+        let config = Config {
+            pattern: PatternEngine::new(".", false, false).unwrap(),
+            files: files.clone(),
+            recursive: false,
+            count: false,
+            invert_match: false,
+            before_context: 0,
+            after_context: 0,
+            globs: Vec::new(),
+            threads: 4,
+            hidden: false,
+            no_ignore: false,
+            follow: false,
+            json: false,
+        };
+
+        let pool = ThreadPoolBuilder::new().num_threads(4).build().unwrap();
+        let outcomes: Vec<MyResult<SearchOutcome>> =
+            pool.install(|| entries.par_iter().map(|e| search_entry(e, &config)).collect());
+
+        let found: Vec<String> = outcomes
+            .into_iter()
+            .map(|outcome| match outcome.unwrap() {
+                SearchOutcome::Found { filename, .. } => filename,
+                other => panic!("expected Found, got {:?}", std::mem::discriminant(&other)),
+            })
+            .collect();
+
+        assert_eq!(found, files);
+    }
+
     #[test]
     fn test_find_lines() {
         let text = b"Lorem\nIpsum\r\nDOLOR";
         // The pattern _or_ should match the one line, "Lorem"
-        let re1 = Regex::new("or").unwrap();
-        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re1, false);
+        let re1 = PatternEngine::new("or", false, false).unwrap();
+        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re1, false, 0, 0);
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 1);
         // When inverted, the function should match the other two lines
-        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re1, true);
+        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re1, true, 0, 0);
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 2);
         // This regex will be case-insensitive
-        let re2 = RegexBuilder::new("or")
-            .case_insensitive(true)
-            .build()
-            .unwrap();
+        let re2 = PatternEngine::new("or", true, false).unwrap();
         // The two lines "Lorem" and "DOLOR" should match
-        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re2, false);
+        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re2, false, 0, 0);
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 2);
         // When inverted, the one remaining line should match
-        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re2, true);
+        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re2, true, 0, 0);
         assert!(matches.is_ok());
         assert_eq!(matches.unwrap().len(), 1);
     }
+
+    #[test]
+    fn test_find_lines_with_context_single_match() {
+        let text = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let re = PatternEngine::new("four", false, false).unwrap();
+        let matches =
+            find_lines_with_default_strategy(Cursor::new(&text), &re, false, 1, 1).unwrap();
+
+        let got: Vec<(usize, bool)> = matches.iter().map(|m| (m.line, m.is_match)).collect();
+        assert_eq!(got, vec![(3, false), (4, true), (5, false)]);
+    }
+
+    #[test]
+    fn test_find_lines_with_context_overlapping_matches() {
+        // Matches on consecutive lines (3 and 4) have context windows that
+        // overlap, so the merged region should have no duplicated lines.
+        let text = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let re = PatternEngine::new("three|four", false, false).unwrap();
+        let matches =
+            find_lines_with_default_strategy(Cursor::new(&text), &re, false, 1, 1).unwrap();
+
+        let got: Vec<(usize, bool)> = matches.iter().map(|m| (m.line, m.is_match)).collect();
+        assert_eq!(
+            got,
+            vec![(2, false), (3, true), (4, true), (5, false)]
+        );
+
+        // The lines are contiguous, so print_matches would not emit a `--`
+        // group separator between them.
+        assert!(got.windows(2).all(|w| w[1].0 == w[0].0 + 1));
+    }
+
+    #[test]
+    fn test_find_lines_with_context_gap_between_matches() {
+        // Matches on lines 2 and 6 have disjoint context windows (lines 3
+        // and 5 are not adjacent), so a `--` group separator belongs
+        // between them.
+        let text = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let re = PatternEngine::new("two|six", false, false).unwrap();
+        let matches =
+            find_lines_with_default_strategy(Cursor::new(&text), &re, false, 1, 1).unwrap();
+
+        let got: Vec<(usize, bool)> = matches.iter().map(|m| (m.line, m.is_match)).collect();
+        assert_eq!(
+            got,
+            vec![(1, false), (2, true), (3, false), (5, false), (6, true), (7, false)]
+        );
+
+        let gap = got
+            .windows(2)
+            .any(|w| w[1].0 > w[0].0 + 1);
+        assert!(gap, "expected a line-number gap between the two match windows");
+    }
+
+    #[test]
+    fn test_pattern_engine_pcre2() {
+        let text = b"foobar\nfoobaz\n";
+        // A lookahead that `regex` can't express works under the PCRE2 engine
+        let re = PatternEngine::new("foo(?=bar)", false, true).unwrap();
+        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re, false, 0, 0);
+        assert!(matches.is_ok());
+        assert_eq!(matches.unwrap().len(), 1);
+
+        // Case-insensitivity still works under the PCRE2 backend
+        let re = PatternEngine::new("FOO(?=BAR)", true, true).unwrap();
+        let matches = find_lines_with_default_strategy(Cursor::new(&text), &re, false, 0, 0);
+        assert!(matches.is_ok());
+        assert_eq!(matches.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_glob_filter() {
+        // A plain glob matches against the whole path, with `*` stopping at `/`
+        let filter = GlobFilter::new(&["*.rs".to_string()]).unwrap();
+        assert!(filter.is_match("lib.rs"));
+        assert!(!filter.is_match("src/lib.rs"));
+
+        // `**/` matches zero or more leading path components
+        let filter = GlobFilter::new(&["**/*.rs".to_string()]).unwrap();
+        assert!(filter.is_match("lib.rs"));
+        assert!(filter.is_match("src/matcher/mod.rs"));
+
+        // A negative pattern excludes paths that would otherwise be kept
+        let filter =
+            GlobFilter::new(&["**/*.rs".to_string(), "!target/**/*".to_string()]).unwrap();
+        assert!(filter.is_match("src/lib.rs"));
+        assert!(!filter.is_match("target/debug/lib.rs"));
+
+        // With no positive patterns, everything is kept unless excluded
+        let filter = GlobFilter::new(&["!**/*.txt".to_string()]).unwrap();
+        assert!(filter.is_match("src/lib.rs"));
+        assert!(!filter.is_match("src/notes.txt"));
+    }
+
+    #[test]
+    fn test_needs_group_separator_without_context() {
+        // With no -A/-B/-C requested, `matches` holds only match lines, and
+        // a gap between them (e.g. matches on lines 1 and 6) is expected,
+        // ordinary output -- never a `--` separator.
+        assert!(!needs_group_separator(Some(1), 6, false));
+        assert!(!needs_group_separator(Some(1), 2, false));
+        assert!(!needs_group_separator(None, 1, false));
+    }
+
+    #[test]
+    fn test_needs_group_separator_with_context() {
+        // With context requested, a line-number gap does mean a disjoint
+        // context window and should be separated.
+        assert!(needs_group_separator(Some(3), 5, true));
+        // Adjacent or overlapping windows should not be separated.
+        assert!(!needs_group_separator(Some(3), 4, true));
+        assert!(!needs_group_separator(None, 1, true));
+    }
+
+    #[test]
+    fn test_submatch_byte_offsets() {
+        let pattern = PatternEngine::new("foo", false, false).unwrap();
+        let submatches = pattern.find_submatches("foo bar foo").unwrap();
+
+        assert_eq!(submatches.len(), 2);
+        assert_eq!((submatches[0].start, submatches[0].end), (0, 3));
+        assert_eq!(submatches[0].text, "foo");
+        assert_eq!((submatches[1].start, submatches[1].end), (8, 11));
+        assert_eq!(submatches[1].text, "foo");
+    }
+
+    #[test]
+    fn test_json_record_serialization() {
+        let record = JsonRecord {
+            path: "tests/inputs/fox.txt".to_string(),
+            line_number: 1,
+            line: "the quick brown fox\n".to_string(),
+            submatches: vec![Submatch {
+                start: 16,
+                end: 19,
+                text: "fox".to_string(),
+            }],
+        };
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(
+            json,
+            r#"{"path":"tests/inputs/fox.txt","line_number":1,"line":"the quick brown fox\n","submatches":[{"start":16,"end":19,"text":"fox"}]}"#
+        );
+    }
+
+    #[test]
+    fn test_json_count_record_serialization() {
+        // `--json --count` emits one count record per file instead of a
+        // per-match record.
+        let record = JsonCountRecord {
+            path: "tests/inputs/fox.txt".to_string(),
+            count: 2,
+        };
+
+        let json = serde_json::to_string(&record).unwrap();
+        assert_eq!(json, r#"{"path":"tests/inputs/fox.txt","count":2}"#);
+    }
 }